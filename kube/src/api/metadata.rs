@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+/// Type information that is flattened into every Kubernetes object
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct TypeMeta {
+    /// The version of the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+
+    /// The name of the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+/// A reference to the controlling object of a Kubernetes resource
+///
+/// See <https://kubernetes.io/docs/concepts/overview/working-with-objects/owners-dependents/>
+#[derive(Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnerReference {
+    /// The API version of the owner
+    pub api_version: String,
+
+    /// The kind of the owner
+    pub kind: String,
+
+    /// The name of the owner
+    pub name: String,
+
+    /// The uid of the owner
+    pub uid: String,
+
+    /// Whether the owner is the managing controller of this object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller: Option<bool>,
+
+    /// Whether deletion of this object is blocked until the owner is deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_owner_deletion: Option<bool>,
+}
+
+/// Metadata that all persisted Kubernetes resources carry
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectMeta {
+    /// Name of the object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Namespace the object lives in, if namespaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// An opaque value representing the internal version of this object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_version: Option<String>,
+
+    /// A UID that uniquely identifies this object for its lifetime
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+
+    /// Map of string keys and values used to organize and categorize objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Unstructured key-value map used to store arbitrary metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// References to the objects that own this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_references: Option<Vec<OwnerReference>>,
+}
+
+/// Metadata that all Kubernetes list responses carry
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct ListMeta {
+    /// An opaque value representing the internal version of this list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_version: Option<String>,
+
+    /// A continuation token for requesting the next page of results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+/// Common information accessible on any typed Kubernetes object
+///
+/// Implemented for any object carrying an [`ObjectMeta`](./struct.ObjectMeta.html),
+/// so that generic code (e.g. in `runtime::Controller`) can pull out the bits it
+/// needs without knowing the concrete resource type.
+pub trait Meta: Sized {
+    /// Return the name of the object
+    fn name(&self) -> String;
+
+    /// Return the namespace of the object, if any
+    fn namespace(&self) -> Option<String>;
+
+    /// Return the resource version of the object, if any
+    fn resource_ver(&self) -> Option<String>;
+
+    /// Return the owner references of the object, if any
+    fn owner_references(&self) -> &[OwnerReference];
+}
@@ -0,0 +1,71 @@
+//! The low-level HTTP client shared by every `Api<K>`.
+//!
+//! `Api::get`/`create`/`replace`/... build a `reqwest::Request` for a call and
+//! hand it to [`APIClient::send`], which attaches the current bearer token
+//! and, on a 401, refreshes it once and retries before giving up.
+
+use std::sync::Arc;
+
+use reqwest::{Client, StatusCode};
+
+use crate::{config::incluster_config::TokenProvider, Error, Result};
+
+/// Talks to the Kubernetes API server on behalf of every `Api<K>` built from
+/// it. Cheap to `clone()`: the underlying `reqwest::Client`, and the
+/// `TokenProvider` when present, are both reference-counted internally.
+#[derive(Clone)]
+pub struct APIClient {
+    cluster_url: String,
+    http: Client,
+    token_provider: Option<Arc<TokenProvider>>,
+}
+
+impl APIClient {
+    /// Build a client with no bearer-token auth, e.g. for a kubeconfig that
+    /// authenticates via client certificates instead.
+    pub fn new(cluster_url: String, http: Client) -> Self {
+        APIClient {
+            cluster_url,
+            http,
+            token_provider: None,
+        }
+    }
+
+    /// Build a client authenticating every request with `token_provider`,
+    /// e.g. the in-cluster service account token from [`TokenProvider::new`].
+    pub fn with_token_provider(cluster_url: String, http: Client, token_provider: TokenProvider) -> Self {
+        APIClient {
+            cluster_url,
+            http,
+            token_provider: Some(Arc::new(token_provider)),
+        }
+    }
+
+    pub(crate) fn cluster_url(&self) -> &str {
+        &self.cluster_url
+    }
+
+    /// Send a request built by `build_req`, attaching the current bearer
+    /// token if this client has a `TokenProvider`. On a 401, refreshes the
+    /// token once and retries, returning the retry's response whatever its
+    /// status.
+    pub(crate) async fn send(&self, build_req: impl Fn() -> reqwest::Request) -> Result<reqwest::Response> {
+        let resp = self.send_authed(build_req()).await?;
+        let provider = match (&self.token_provider, resp.status()) {
+            (Some(provider), StatusCode::UNAUTHORIZED) => provider,
+            _ => return Ok(resp),
+        };
+        provider.invalidate().await?;
+        self.send_authed(build_req()).await
+    }
+
+    async fn send_authed(&self, mut req: reqwest::Request) -> Result<reqwest::Response> {
+        if let Some(provider) = &self.token_provider {
+            let token = provider.token().await?;
+            let value = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|e| Error::KubeConfig(format!("token is not a valid header value: {}", e)))?;
+            req.headers_mut().insert(reqwest::header::AUTHORIZATION, value);
+        }
+        self.http.execute(req).await.map_err(Error::ReqwestError)
+    }
+}
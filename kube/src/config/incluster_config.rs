@@ -1,7 +1,11 @@
-use std::env;
+use std::{
+    env,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
 
-use crate::{Error, Result};
-use reqwest::Certificate;
+use crate::{client::APIClient, Error, Result};
+use reqwest::{Certificate, Client};
 
 use crate::config::utils;
 
@@ -11,6 +15,10 @@ const SERVICE_TOKENFILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/t
 const SERVICE_CERTFILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt";
 const SERVICE_DEFAULT_NS: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
 
+/// How long `TokenProvider` trusts its cached token before re-reading the
+/// file on its own, regardless of whether a caller has observed a 401.
+const TOKEN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Returns kubernetes address from specified environment variables.
 pub fn kube_server() -> Option<String> {
     let f = |(h, p)| format!("https://{}:{}", h, p);
@@ -26,10 +34,80 @@ fn kube_port() -> Option<String> {
 }
 
 /// Returns token from specified path in cluster.
+///
+/// Reads the file once; a long-running process should use [`TokenProvider`]
+/// instead so rotated tokens are picked up automatically.
 pub fn load_token() -> Result<String> {
     utils::data_or_file(&None, &Some(SERVICE_TOKENFILE))
 }
 
+/// A cached, auto-refreshing view of the in-cluster service account token.
+///
+/// Kubelet rewrites the token file in place as bound/projected tokens expire
+/// (commonly every hour), so a `Controller` built around a single `load_token()`
+/// call would start getting 401s and never recover. `TokenProvider` instead
+/// keeps the last-read token cached, transparently re-reading the token file
+/// whenever the cache goes stale or a caller explicitly `invalidate`s it after
+/// observing a 401 from the API server.
+pub struct TokenProvider {
+    cache: RwLock<CachedToken>,
+    refresh_interval: Duration,
+    load_token: fn() -> Result<String>,
+}
+
+struct CachedToken {
+    token: String,
+    fetched_at: Instant,
+}
+
+impl TokenProvider {
+    /// Read the token file once to seed the cache.
+    pub fn new() -> Result<Self> {
+        Self::from_loader(load_token, TOKEN_REFRESH_INTERVAL)
+    }
+
+    fn from_loader(load_token: fn() -> Result<String>, refresh_interval: Duration) -> Result<Self> {
+        Ok(TokenProvider {
+            cache: RwLock::new(CachedToken {
+                token: load_token()?,
+                fetched_at: Instant::now(),
+            }),
+            refresh_interval,
+            load_token,
+        })
+    }
+
+    /// Return the current token, re-reading the token file first if the cache
+    /// has gone stale.
+    pub async fn token(&self) -> Result<String> {
+        if self.cache.read().unwrap().fetched_at.elapsed() > self.refresh_interval {
+            self.refresh().await?;
+        }
+        Ok(self.cache.read().unwrap().token.clone())
+    }
+
+    /// Force an immediate re-read of the token file, bypassing the refresh
+    /// interval. Callers should do this after the API server rejects a
+    /// request with 401 using the currently cached token.
+    pub async fn invalidate(&self) -> Result<String> {
+        self.refresh().await?;
+        Ok(self.cache.read().unwrap().token.clone())
+    }
+
+    /// Re-read the token file on a blocking thread, so a reconcile loop
+    /// driving this off the async path never stalls a tokio worker on disk I/O.
+    async fn refresh(&self) -> Result<()> {
+        let load_token = self.load_token;
+        let token = tokio::task::spawn_blocking(move || load_token())
+            .await
+            .map_err(|e| Error::KubeConfig(format!("token refresh task panicked: {}", e)))??;
+        let mut cache = self.cache.write().unwrap();
+        cache.token = token;
+        cache.fetched_at = Instant::now();
+        Ok(())
+    }
+}
+
 /// Returns certification from specified path in cluster.
 pub fn load_cert() -> Result<Certificate> {
     let ca = utils::data_or_file_with_base64(&None, &Some(SERVICE_CERTFILE))?;
@@ -41,6 +119,26 @@ pub fn load_default_ns() -> Result<String> {
     utils::data_or_file(&None, &Some(SERVICE_DEFAULT_NS))
 }
 
+/// Build an `APIClient` for the in-cluster API server, trusting its CA cert
+/// and authenticating every request via an auto-refreshing [`TokenProvider`].
+pub fn incluster_client() -> Result<APIClient> {
+    let cluster_url = kube_server().ok_or_else(|| {
+        Error::KubeConfig(format!(
+            "must set {} and {} to use the in-cluster config",
+            SERVICE_HOSTENV, SERVICE_PORTENV
+        ))
+    })?;
+    let http = Client::builder()
+        .add_root_certificate(load_cert()?)
+        .build()
+        .map_err(Error::ReqwestError)?;
+    Ok(APIClient::with_token_provider(
+        cluster_url,
+        http,
+        TokenProvider::new()?,
+    ))
+}
+
 #[test]
 fn test_kube_host() {
     let expected = "fake.io";
@@ -63,3 +161,44 @@ fn test_kube_server() {
     env::set_var(SERVICE_PORTENV, port);
     assert_eq!(kube_server().unwrap(), "https://fake.io:8080");
 }
+
+static REUSE_TEST_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+fn reuse_test_loader() -> Result<String> {
+    let n = REUSE_TEST_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    Ok(format!("token-{}", n))
+}
+
+#[tokio::test]
+async fn token_reuses_the_cached_value_within_the_refresh_interval() {
+    let provider = TokenProvider::from_loader(reuse_test_loader, Duration::from_secs(60)).unwrap();
+    assert_eq!(provider.token().await.unwrap(), "token-0");
+    assert_eq!(provider.token().await.unwrap(), "token-0");
+}
+
+static STALE_TEST_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+fn stale_test_loader() -> Result<String> {
+    let n = STALE_TEST_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    Ok(format!("token-{}", n))
+}
+
+#[tokio::test]
+async fn token_re_reads_once_the_refresh_interval_has_elapsed() {
+    let provider = TokenProvider::from_loader(stale_test_loader, Duration::from_millis(10)).unwrap();
+    assert_eq!(provider.token().await.unwrap(), "token-0");
+    tokio::time::delay_for(Duration::from_millis(30)).await;
+    assert_eq!(provider.token().await.unwrap(), "token-1");
+}
+
+static INVALIDATE_TEST_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+fn invalidate_test_loader() -> Result<String> {
+    let n = INVALIDATE_TEST_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    Ok(format!("token-{}", n))
+}
+
+#[tokio::test]
+async fn invalidate_forces_a_refresh_within_the_interval() {
+    let provider = TokenProvider::from_loader(invalidate_test_loader, Duration::from_secs(60)).unwrap();
+    assert_eq!(provider.token().await.unwrap(), "token-0");
+    assert_eq!(provider.invalidate().await.unwrap(), "token-1");
+    assert_eq!(provider.token().await.unwrap(), "token-1");
+}
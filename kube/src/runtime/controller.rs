@@ -1,20 +1,37 @@
 use crate::{
     api::{
         resource::{ListParams, Resource},
-        Meta, WatchEvent,
+        Api, Meta, WatchEvent,
     },
     client::APIClient,
-    runtime::informer::Informer,
+    runtime::{
+        informer::Informer, is_not_found, leader_election::LeaseLock, LeadershipEvent, Metrics,
+        NoopMetrics, ObjectKey, ReconcileOutcome, WorkQueue,
+    },
     Error, Result,
 };
-use futures::{channel::mpsc, lock::Mutex, stream, Stream, StreamExt};
+use futures::{Future, StreamExt};
 use serde::de::DeserializeOwned;
-use std::{collections::VecDeque, convert::TryFrom, sync::Arc};
+use std::{
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Number of reconcile workers `Controller::run` drives concurrently
+const DEFAULT_CONCURRENCY: usize = 5;
+
+/// How long to wait before retrying a watch whose initial list/watch setup
+/// failed (CRD not yet established, transient API error, auth hiccup).
+const WATCH_START_RETRY_DELAY: Duration = Duration::from_secs(5);
 
 /// An object to be reconciled
 ///
-/// The type that is pulled out of Controller::poll
-#[derive(Debug, Clone)]
+/// The type that is pulled out of the work queue and handed to the reconciler
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ReconcileEvent {
     pub name: String,
     pub namespace: Option<String>,
@@ -49,6 +66,27 @@ where
     }
 }
 
+/// The outcome of a single reconcile, telling the driver what to do next
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileAction {
+    /// Re-queue the object after this duration even if nothing else triggers it
+    pub requeue_after: Option<Duration>,
+}
+
+/// Data handed to the reconciler alongside the `ReconcileEvent` on every invocation
+#[derive(Clone)]
+pub struct Context<K>
+where
+    K: Clone + DeserializeOwned + Meta,
+{
+    /// The `Api` for the root resource being reconciled
+    pub api: Api<K>,
+
+    /// The live object fetched via `api` just before this reconcile, or
+    /// `None` if it was already deleted by the time the driver got to it.
+    pub obj: Option<K>,
+}
+
 /// A controller for a kubernetes object K
 pub struct Controller<K>
 where
@@ -57,73 +95,437 @@ where
     client: APIClient,
     resource: Resource,
     informers: Vec<Informer<K>>,
-    queue: Arc<Mutex<VecDeque<ReconcileEvent>>>,
-    channel: (
-        mpsc::UnboundedSender<Result<ReconcileEvent>>,
-        mpsc::UnboundedReceiver<Result<ReconcileEvent>>,
-    ),
+    queue: WorkQueue,
+    metrics: Arc<dyn Metrics>,
 }
 
-
 impl<K: 'static> Controller<K>
 where
     K: Clone + DeserializeOwned + Meta + Send + Sync,
 {
-    /// Create a controller with a kube client on a kube resource
+    /// Create a controller with a kube client on a kube resource. `run()`
+    /// reconciles on every change to `r` itself; use `owns()` to also
+    /// reconcile the owner when one of its children changes.
     pub fn new(client: APIClient, r: Resource) -> Self {
+        let metrics: Arc<dyn Metrics> = Arc::new(NoopMetrics);
         Controller {
             client: client,
             resource: r,
             informers: vec![],
-            queue: Default::default(),
-            channel: mpsc::unbounded(),
+            queue: WorkQueue::new().with_metrics(metrics.clone()),
+            metrics,
         }
     }
 
-    /// Create internal informers for an associated kube resource
-    ///
-    /// TODO: this needs to only find resources with a property matching root resource
+    /// Watch an associated kube resource, mapping its events back to the owning
+    /// root resource rather than reconciling the child directly.
     pub fn owns(mut self, r: Resource, lp: ListParams) -> Self {
-        self.informers.push(Informer::new(self.client.clone(), lp, r));
+        self.informers
+            .push(Informer::new(self.client.clone(), lp, r));
         self
     }
 
-    /// Poll reconcile events through all internal informers
-    /*    pub async fn poll(&self) -> Result<impl Stream<Item = Result<ReconcileEvent>>> {
-            // TODO: debounce rx events
-            //let stream = stream::from(self.channel.1);
-                //futures::stream::try_unfold(self.channel.1, |rx| async move { async { return rx.try_next() } });
-            Ok(self.channel.1)
-        }
-    */
-    /// Initialize
+    /// Report work queue and reconcile transitions to `metrics` instead of the
+    /// default no-op implementation. Wire this to a `prometheus` registry (or
+    /// similar) served from an HTTP endpoint the caller stands up themselves.
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.queue = self.queue.with_metrics(metrics.clone());
+        self.metrics = metrics;
+        self
+    }
+
+    /// Initialize the controller: start polling every informer in the background,
+    /// debouncing and deduplicating what they observe into the work queue rather
+    /// than shovelling raw events straight at the reconciler.
     pub fn init(self) -> Self {
         info!("Starting Controller for {:?}", self.resource);
 
-        // 1. poll informers in parallel and push results to queue
+        self.queue.spawn_delay_driver();
+
+        // 1. watch the root resource itself, mapping every event straight onto
+        // its own ReconcileEvent. This is what makes `run()` do anything at
+        // all without a single `.owns()` call: the informers below only ever
+        // cover *owned* children, never the resource being reconciled.
+        {
+            let primary = Informer::new(
+                self.client.clone(),
+                ListParams::default(),
+                self.resource.clone(),
+            );
+            let queue = self.queue.clone();
+            tokio::spawn(async move {
+                // Retry the initial list/watch setup every
+                // `WATCH_START_RETRY_DELAY` instead of
+                // `unwrap()`ing it: this is the only thing driving root-resource
+                // reconciliation without a single `.owns()` call, so a transient
+                // failure here must not silently and permanently stop it.
+                let mut poll_i = loop {
+                    match primary.poll().await {
+                        Ok(stream) => break stream.boxed(),
+                        Err(e) => {
+                            warn!(
+                                "failed to start watch for primary resource, retrying in {:?}: {}",
+                                WATCH_START_RETRY_DELAY, e
+                            );
+                            tokio::time::delay_for(WATCH_START_RETRY_DELAY).await;
+                        }
+                    }
+                };
+                while let Some(ev) = poll_i.next().await {
+                    match ev {
+                        Ok(WatchEvent::Added(o))
+                        | Ok(WatchEvent::Modified(o))
+                        | Ok(WatchEvent::Deleted(o)) => {
+                            queue.add(ReconcileEvent::from(o).into()).await;
+                        }
+                        Ok(WatchEvent::Error(e)) => warn!("watch error: {}", e),
+                        Err(e) => warn!("watch stream error: {}", e),
+                    }
+                }
+            });
+        }
+
+        // 2. poll owned-child informers in parallel, map their events onto
+        // the owning root resource, and push deduplicated keys onto the queue
         for inf in self.informers.clone() {
-            // TODO: ownership move?
-            //let queue = self.queue.clone();
-            let tx = self.channel.0.clone();
+            let queue = self.queue.clone();
+            let resource = self.resource.clone();
             tokio::spawn(async move {
                 let mut poll_i = inf.poll().await.unwrap().boxed();
                 while let Some(ev) = poll_i.next().await {
                     match ev {
-                        Ok(wi) => {
-                            let ri = ReconcileEvent::try_from(wi);
-                            //(*queue.lock().await).push_back(ri);
-                            tx.unbounded_send(ri).expect("channel can receive");
+                        Ok(WatchEvent::Added(o))
+                        | Ok(WatchEvent::Modified(o))
+                        | Ok(WatchEvent::Deleted(o)) => {
+                            if let Some(ri) = owning_reconcile_event(&resource, &o) {
+                                queue.add(ri.into()).await;
+                            }
                         }
-                        _ => unimplemented!(),
-                        //Err(e) => tx.unbounded_send(Err(e)),
+                        Ok(WatchEvent::Error(e)) => warn!("watch error: {}", e),
+                        Err(e) => warn!("watch stream error: {}", e),
                     }
                 }
             });
         }
-        // TODO: init main informer
-        // TODO: queue up events
-        // TODO: debounce events
-        // TODO: trigger events
         self
     }
+
+    /// Drive reconciliation: initialize the informers, then pop keys off the
+    /// work queue and hand each one to `reconciler`. On success the key is
+    /// forgotten (resetting its backoff) and re-queued after `requeue_after`
+    /// if set; on failure it is re-queued with exponential backoff via
+    /// `add_rate_limited` so transient errors don't spin hot.
+    pub async fn run<F, Fut>(self, reconciler: F)
+    where
+        F: Fn(ReconcileEvent, Context<K>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ReconcileAction>> + Send,
+    {
+        self.run_gated(Arc::new(AtomicBool::new(true)), reconciler)
+            .await
+    }
+
+    /// Like `run`, but only reconcile while `lock` is held, pausing whenever
+    /// leadership is lost to another replica and resuming once it is
+    /// reacquired. Use this to run several replicas of the same controller
+    /// for availability without them reconciling concurrently.
+    pub async fn run_with_leader_election<F, Fut>(self, lock: LeaseLock, reconciler: F)
+    where
+        F: Fn(ReconcileEvent, Context<K>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ReconcileAction>> + Send,
+    {
+        let is_leader = Arc::new(AtomicBool::new(false));
+        let mut events = crate::runtime::leader_election::run_leader_election(lock);
+        {
+            let is_leader = is_leader.clone();
+            tokio::spawn(async move {
+                while let Some(event) = events.next().await {
+                    match event {
+                        LeadershipEvent::Acquired => {
+                            info!("acquired leadership, resuming reconciliation");
+                            is_leader.store(true, Ordering::SeqCst);
+                        }
+                        LeadershipEvent::Lost => {
+                            warn!("lost leadership, pausing reconciliation");
+                            is_leader.store(false, Ordering::SeqCst);
+                        }
+                    }
+                }
+            });
+        }
+        self.run_gated(is_leader, reconciler).await
+    }
+
+    /// Shared driver behind `run`/`run_with_leader_election`: only pop and
+    /// reconcile keys while `is_leader` is true.
+    async fn run_gated<F, Fut>(self, is_leader: Arc<AtomicBool>, reconciler: F)
+    where
+        F: Fn(ReconcileEvent, Context<K>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ReconcileAction>> + Send,
+    {
+        let this = self.init();
+        let client = this.client.clone();
+        let resource = this.resource.clone();
+        let reconciler = Arc::new(reconciler);
+
+        let workers = (0..DEFAULT_CONCURRENCY).map(|_| {
+            let queue = this.queue.clone();
+            let client = client.clone();
+            let resource = resource.clone();
+            let reconciler = reconciler.clone();
+            let is_leader = is_leader.clone();
+            let metrics = this.metrics.clone();
+            tokio::spawn(async move {
+                loop {
+                    if !is_leader.load(Ordering::SeqCst) {
+                        tokio::time::delay_for(Duration::from_millis(50)).await;
+                        continue;
+                    }
+                    let key = match queue.get().await {
+                        Some(key) => key,
+                        None => {
+                            tokio::time::delay_for(Duration::from_millis(50)).await;
+                            continue;
+                        }
+                    };
+                    metrics.reconcile_started();
+                    let started_at = Instant::now();
+                    let outcome = reconcile_one(&client, &resource, &reconciler, &key).await;
+                    let reconcile_outcome = if outcome.is_ok() {
+                        ReconcileOutcome::Success
+                    } else {
+                        ReconcileOutcome::Error
+                    };
+                    metrics.reconcile_finished(reconcile_outcome, started_at.elapsed());
+                    apply_outcome(&queue, &key, &outcome).await;
+                    queue.done(&key).await;
+                }
+            })
+        });
+        futures::future::join_all(workers).await;
+    }
+}
+
+/// Fetch the live object for `key` and hand it off to the reconciler.
+///
+/// The `Api` is built fresh per call, namespaced to `key.namespace` rather
+/// than `resource`'s own, since a child handed to us via `owns()` (and hence
+/// `key`) may live in a different namespace than whatever `resource` was
+/// constructed with.
+///
+/// A `NotFound` here just means the object was already deleted by the time we
+/// got to it; the reconciler still runs with `Context::obj` set to `None` (so
+/// finalizer/cleanup logic keyed off `key` alone gets a chance), instead of
+/// being treated as a failure that spins the key through `add_rate_limited`
+/// forever.
+async fn reconcile_one<K, F, Fut>(
+    client: &APIClient,
+    resource: &Resource,
+    reconciler: &F,
+    key: &ObjectKey,
+) -> Result<ReconcileAction>
+where
+    K: Clone + DeserializeOwned + Meta,
+    F: Fn(ReconcileEvent, Context<K>) -> Fut,
+    Fut: Future<Output = Result<ReconcileAction>>,
+{
+    let mut resource = resource.clone();
+    resource.namespace = key.namespace.clone();
+    let api = Api::new(client.clone(), resource);
+
+    let obj = match api.get(&key.name).await {
+        Ok(obj) => Some(obj),
+        Err(e) if is_not_found(&e) => None,
+        Err(e) => return Err(e),
+    };
+    reconciler(key.clone().into(), Context { api, obj }).await
+}
+
+/// Apply a reconcile's outcome to the queue: on success, forget the key's
+/// failure count and re-queue it after `requeue_after` if set; on failure,
+/// re-queue it with exponential backoff via `add_rate_limited` so a
+/// transient error doesn't spin the key hot.
+async fn apply_outcome(queue: &WorkQueue, key: &ObjectKey, outcome: &Result<ReconcileAction>) {
+    match outcome {
+        Ok(action) => {
+            queue.forget(key).await;
+            if let Some(dur) = action.requeue_after {
+                queue.add_after(key.clone(), dur).await;
+            }
+        }
+        Err(e) => {
+            warn!("reconcile failed for {:?}: {}", key, e);
+            queue.add_rate_limited(key.clone()).await;
+        }
+    }
+}
+
+/// Map a child object's watch event back onto its owning root resource, by
+/// finding the `ownerReferences` entry matching `resource`'s `apiVersion`/`kind`
+/// (preferring the entry marked `controller: true`, as Kubernetes guarantees at
+/// most one). Returns `None` if the child has no owner reference to the root
+/// resource, in which case the event should be dropped.
+fn owning_reconcile_event<K: Meta>(resource: &Resource, child: &K) -> Option<ReconcileEvent> {
+    let owner = child
+        .owner_references()
+        .iter()
+        .filter(|o| o.kind == resource.kind && o.api_version == resource.api_version)
+        .max_by_key(|o| o.controller.unwrap_or(false))?;
+    Some(ReconcileEvent {
+        name: owner.name.clone(),
+        namespace: Meta::namespace(child),
+    })
+}
+
+#[derive(Clone)]
+struct FakeChild {
+    namespace: Option<String>,
+    owners: Vec<crate::api::OwnerReference>,
+}
+
+impl Meta for FakeChild {
+    fn name(&self) -> String {
+        "child".into()
+    }
+
+    fn namespace(&self) -> Option<String> {
+        self.namespace.clone()
+    }
+
+    fn resource_ver(&self) -> Option<String> {
+        None
+    }
+
+    fn owner_references(&self) -> &[crate::api::OwnerReference] {
+        &self.owners
+    }
+}
+
+fn deployment_resource() -> Resource {
+    Resource {
+        group: "apps".into(),
+        version: "v1".into(),
+        kind: "Deployment".into(),
+        api_version: "apps/v1".into(),
+        namespace: None,
+    }
+}
+
+#[test]
+fn owning_reconcile_event_finds_matching_owner() {
+    let child = FakeChild {
+        namespace: Some("ns".into()),
+        owners: vec![crate::api::OwnerReference {
+            api_version: "apps/v1".into(),
+            kind: "Deployment".into(),
+            name: "parent".into(),
+            uid: "1".into(),
+            controller: Some(true),
+            block_owner_deletion: None,
+        }],
+    };
+    let event = owning_reconcile_event(&deployment_resource(), &child).unwrap();
+    assert_eq!(event.name, "parent");
+    assert_eq!(event.namespace, Some("ns".into()));
+}
+
+#[test]
+fn owning_reconcile_event_ignores_unrelated_owner() {
+    let child = FakeChild {
+        namespace: None,
+        owners: vec![crate::api::OwnerReference {
+            api_version: "v1".into(),
+            kind: "ConfigMap".into(),
+            name: "cm".into(),
+            uid: "1".into(),
+            controller: None,
+            block_owner_deletion: None,
+        }],
+    };
+    assert!(owning_reconcile_event(&deployment_resource(), &child).is_none());
+}
+
+#[test]
+fn owning_reconcile_event_prefers_the_controller_owner() {
+    let child = FakeChild {
+        namespace: None,
+        owners: vec![
+            crate::api::OwnerReference {
+                api_version: "apps/v1".into(),
+                kind: "Deployment".into(),
+                name: "non-controller".into(),
+                uid: "1".into(),
+                controller: Some(false),
+                block_owner_deletion: None,
+            },
+            crate::api::OwnerReference {
+                api_version: "apps/v1".into(),
+                kind: "Deployment".into(),
+                name: "controller".into(),
+                uid: "2".into(),
+                controller: Some(true),
+                block_owner_deletion: None,
+            },
+        ],
+    };
+    let event = owning_reconcile_event(&deployment_resource(), &child).unwrap();
+    assert_eq!(event.name, "controller");
+}
+
+fn object_key(name: &str) -> ObjectKey {
+    ObjectKey {
+        name: name.into(),
+        namespace: None,
+    }
+}
+
+#[tokio::test]
+async fn apply_outcome_success_without_requeue_after_does_not_requeue() {
+    let queue = WorkQueue::new();
+    queue.spawn_delay_driver();
+    let key = object_key("a");
+    queue.add(key.clone()).await;
+    queue.get().await;
+
+    apply_outcome(&queue, &key, &Ok(ReconcileAction::default())).await;
+    queue.done(&key).await;
+
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(queue.get().await, None);
+}
+
+#[tokio::test]
+async fn apply_outcome_success_requeues_after_the_requested_duration() {
+    let queue = WorkQueue::new();
+    queue.spawn_delay_driver();
+    let key = object_key("a");
+    queue.add(key.clone()).await;
+    queue.get().await;
+
+    apply_outcome(
+        &queue,
+        &key,
+        &Ok(ReconcileAction {
+            requeue_after: Some(Duration::from_millis(10)),
+        }),
+    )
+    .await;
+    queue.done(&key).await;
+
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(queue.get().await, Some(key));
+}
+
+#[tokio::test]
+async fn apply_outcome_error_retries_with_backoff() {
+    let queue = WorkQueue::new();
+    queue.spawn_delay_driver();
+    let key = object_key("a");
+    queue.add(key.clone()).await;
+    queue.get().await;
+
+    apply_outcome(&queue, &key, &Err(Error::KubeConfig("boom".into()))).await;
+    queue.done(&key).await;
+
+    tokio::time::delay_for(Duration::from_millis(100)).await;
+    assert_eq!(queue.get().await, Some(key));
 }
@@ -0,0 +1,266 @@
+//! Lease-based leader election for running a `Controller` with multiple
+//! replicas, so only one of them reconciles at a time.
+//!
+//! Built on the `coordination.k8s.io/v1` `Lease` resource, the same primitive
+//! client-go's `leaderelection` package uses.
+
+use chrono::{DateTime, Utc};
+use futures::{channel::mpsc, StreamExt};
+use std::time::Duration;
+
+use crate::{
+    api::{Api, Meta, ObjectMeta, OwnerReference, PostParams, Resource},
+    client::APIClient,
+    runtime::is_not_found,
+    Result,
+};
+
+/// The `coordination.k8s.io/v1` `Lease` resource
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Lease {
+    pub metadata: ObjectMeta,
+    pub spec: LeaseSpec,
+}
+
+/// `LeaseSpec` as defined by `coordination.k8s.io/v1`
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaseSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holder_identity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_duration_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acquire_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renew_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_transitions: Option<i32>,
+}
+
+impl Meta for Lease {
+    fn name(&self) -> String {
+        self.metadata.name.clone().unwrap_or_default()
+    }
+
+    fn namespace(&self) -> Option<String> {
+        self.metadata.namespace.clone()
+    }
+
+    fn resource_ver(&self) -> Option<String> {
+        self.metadata.resource_version.clone()
+    }
+
+    fn owner_references(&self) -> &[OwnerReference] {
+        self.metadata.owner_references.as_deref().unwrap_or(&[])
+    }
+}
+
+fn lease_resource(namespace: &str) -> Resource {
+    Resource {
+        group: "coordination.k8s.io".into(),
+        version: "v1".into(),
+        kind: "Lease".into(),
+        api_version: "coordination.k8s.io/v1".into(),
+        namespace: Some(namespace.into()),
+    }
+}
+
+/// Signals a change in leadership status, emitted when a `LeaseLock` we are
+/// driving through `run_leader_election` transitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeadershipEvent {
+    /// We just became the leader
+    Acquired,
+    /// We just lost leadership, e.g. another replica took over the lease
+    Lost,
+}
+
+/// Drives acquisition and renewal of a single `Lease`, identifying this
+/// replica as `holder_identity`.
+#[derive(Clone)]
+pub struct LeaseLock {
+    api: Api<Lease>,
+    name: String,
+    holder_identity: String,
+    lease_duration: Duration,
+}
+
+impl LeaseLock {
+    /// Create a lock around `namespace/name`, identifying this replica as
+    /// `holder_identity`. The lease counts as expired once it has gone
+    /// `lease_duration` without a renewal.
+    pub fn new(
+        client: APIClient,
+        namespace: &str,
+        name: &str,
+        holder_identity: &str,
+        lease_duration: Duration,
+    ) -> Self {
+        LeaseLock {
+            api: Api::new(client, lease_resource(namespace)),
+            name: name.into(),
+            holder_identity: holder_identity.into(),
+            lease_duration,
+        }
+    }
+
+    /// Try once to acquire or renew the lease. Returns `true` if we hold it
+    /// (and renewed `renewTime`) afterwards, `false` if another replica holds
+    /// an unexpired lease.
+    pub async fn try_acquire_or_renew(&self) -> Result<bool> {
+        match self.api.get(&self.name).await {
+            Ok(mut lease) => {
+                let now = Utc::now();
+                if !lease_claimable(&lease, &self.holder_identity, self.lease_duration, now) {
+                    return Ok(false);
+                }
+                let held_by_us =
+                    lease.spec.holder_identity.as_deref() == Some(self.holder_identity.as_str());
+                if !held_by_us {
+                    lease.spec.acquire_time = Some(now);
+                    lease.spec.lease_transitions =
+                        Some(lease.spec.lease_transitions.unwrap_or(0) + 1);
+                }
+                lease.spec.holder_identity = Some(self.holder_identity.clone());
+                lease.spec.renew_time = Some(now);
+                lease.spec.lease_duration_seconds = Some(self.lease_duration.as_secs() as i32);
+                self.api
+                    .replace(&self.name, &PostParams::default(), lease)
+                    .await?;
+                Ok(true)
+            }
+            Err(e) if is_not_found(&e) => {
+                let now = Utc::now();
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.name.clone()),
+                        ..Default::default()
+                    },
+                    spec: LeaseSpec {
+                        holder_identity: Some(self.holder_identity.clone()),
+                        lease_duration_seconds: Some(self.lease_duration.as_secs() as i32),
+                        acquire_time: Some(now),
+                        renew_time: Some(now),
+                        lease_transitions: Some(0),
+                    },
+                };
+                self.api.create(&PostParams::default(), lease).await?;
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Whether `lease` can be claimed/renewed by `holder_identity` as of `now`:
+/// either it is already held by us, or the current holder hasn't renewed
+/// within `lease_duration` and is therefore expired.
+fn lease_claimable(
+    lease: &Lease,
+    holder_identity: &str,
+    lease_duration: Duration,
+    now: DateTime<Utc>,
+) -> bool {
+    let held_by_us = lease.spec.holder_identity.as_deref() == Some(holder_identity);
+    if held_by_us {
+        return true;
+    }
+    match lease.spec.renew_time {
+        Some(renew_time) => {
+            now.signed_duration_since(renew_time)
+                .to_std()
+                .unwrap_or_default()
+                > lease_duration
+        }
+        None => true,
+    }
+}
+
+/// Spawn the background election loop for `lock`, renewing at roughly a third
+/// of the lease duration while leading, and retrying at the full lease
+/// duration while following. Yields a `LeadershipEvent` each time our status
+/// changes, so callers (e.g. `Controller::run_with_leader_election`) can
+/// pause/resume reconciliation.
+pub fn run_leader_election(lock: LeaseLock) -> mpsc::UnboundedReceiver<LeadershipEvent> {
+    let (tx, rx) = mpsc::unbounded();
+    tokio::spawn(async move {
+        let mut is_leader = false;
+        loop {
+            let result = lock.try_acquire_or_renew().await;
+            let now_leader = matches!(result, Ok(true));
+            if now_leader != is_leader {
+                is_leader = now_leader;
+                let event = if is_leader {
+                    LeadershipEvent::Acquired
+                } else {
+                    LeadershipEvent::Lost
+                };
+                if tx.unbounded_send(event).is_err() {
+                    return; // receiver gone, nothing left to drive
+                }
+            }
+            if let Err(e) = result {
+                warn!("leader election attempt for {} failed: {}", lock.name, e);
+            }
+            let delay = if is_leader {
+                lock.lease_duration / 3
+            } else {
+                lock.lease_duration
+            };
+            tokio::time::delay_for(delay).await;
+        }
+    });
+    rx
+}
+
+#[test]
+fn lease_claimable_when_already_held_by_us() {
+    let lease = Lease {
+        spec: LeaseSpec {
+            holder_identity: Some("me".into()),
+            renew_time: None,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(lease_claimable(&lease, "me", Duration::from_secs(15), Utc::now()));
+}
+
+#[test]
+fn lease_claimable_when_no_previous_holder() {
+    assert!(lease_claimable(
+        &Lease::default(),
+        "me",
+        Duration::from_secs(15),
+        Utc::now()
+    ));
+}
+
+#[test]
+fn lease_claimable_when_other_holders_lease_expired() {
+    let now = Utc::now();
+    let lease = Lease {
+        spec: LeaseSpec {
+            holder_identity: Some("other".into()),
+            renew_time: Some(now - chrono::Duration::seconds(30)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(lease_claimable(&lease, "me", Duration::from_secs(15), now));
+}
+
+#[test]
+fn lease_not_claimable_while_other_holders_lease_is_fresh() {
+    let now = Utc::now();
+    let lease = Lease {
+        spec: LeaseSpec {
+            holder_identity: Some("other".into()),
+            renew_time: Some(now - chrono::Duration::seconds(1)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(!lease_claimable(&lease, "me", Duration::from_secs(15), now));
+}
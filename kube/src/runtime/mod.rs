@@ -0,0 +1,22 @@
+//! Runtime helpers built on top of the raw API and informers
+
+use crate::Error;
+
+mod controller;
+pub use controller::{Context, Controller, ReconcileAction, ReconcileEvent};
+
+pub(crate) mod workqueue;
+pub use workqueue::{ObjectKey, WorkQueue};
+
+pub mod leader_election;
+pub use leader_election::{LeaseLock, LeadershipEvent};
+
+pub mod metrics;
+pub use metrics::{Counters, Metrics, NoopMetrics, ReconcileOutcome};
+
+/// Whether `err` is the API server's 404 for a `get`, as opposed to a
+/// transient or auth failure that callers should surface rather than
+/// paper over (e.g. as "the object doesn't exist yet").
+pub(crate) fn is_not_found(err: &Error) -> bool {
+    matches!(err, Error::Api(e) if e.code == 404)
+}
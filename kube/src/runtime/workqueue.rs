@@ -0,0 +1,289 @@
+//! A rate-limited, deduplicating work queue modeled on client-go's workqueue.
+//!
+//! `Controller` pushes reconcile keys in here instead of onto a raw channel, so
+//! a hot-looping object or a relist storm only ever leaves one outstanding
+//! entry per key queued, and repeated failures back off exponentially rather
+//! than hammering the reconciler.
+
+use futures::lock::Mutex;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::runtime::{
+    controller::ReconcileEvent,
+    metrics::{Metrics, NoopMetrics},
+};
+
+/// Default starting delay for `add_rate_limited`'s exponential backoff.
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(5);
+/// Ceiling for `add_rate_limited`'s exponential backoff.
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(1000);
+/// How often the delay driver wakes up to check for ready entries.
+const DELAY_DRIVER_TICK: Duration = Duration::from_millis(50);
+
+/// Identity of an object to be reconciled, used to dedupe and track queue state.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectKey {
+    pub name: String,
+    pub namespace: Option<String>,
+}
+
+impl From<ReconcileEvent> for ObjectKey {
+    fn from(ev: ReconcileEvent) -> Self {
+        ObjectKey {
+            name: ev.name,
+            namespace: ev.namespace,
+        }
+    }
+}
+
+impl From<ObjectKey> for ReconcileEvent {
+    fn from(k: ObjectKey) -> Self {
+        ReconcileEvent {
+            name: k.name,
+            namespace: k.namespace,
+        }
+    }
+}
+
+/// An entry waiting in the delay heap until `ready_at`.
+struct DelayedEntry {
+    ready_at: Instant,
+    key: ObjectKey,
+}
+
+impl PartialEq for DelayedEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.ready_at == other.ready_at
+    }
+}
+impl Eq for DelayedEntry {}
+
+impl PartialOrd for DelayedEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for DelayedEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.ready_at.cmp(&other.ready_at)
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    ready: VecDeque<ObjectKey>,
+    dirty: HashSet<ObjectKey>,
+    processing: HashSet<ObjectKey>,
+    failures: HashMap<ObjectKey, u32>,
+    delayed: BinaryHeap<Reverse<DelayedEntry>>,
+}
+
+/// A rate-limited, deduplicating queue of `ObjectKey`s awaiting reconciliation.
+///
+/// Cloning a `WorkQueue` shares the same underlying state (it is an `Arc` handle),
+/// so the informer tasks that feed it and the reconcile workers draining it can
+/// each hold their own clone.
+#[derive(Clone)]
+pub struct WorkQueue {
+    inner: Arc<Mutex<Inner>>,
+    base_delay: Duration,
+    max_delay: Duration,
+    metrics: Arc<dyn Metrics>,
+}
+
+impl Default for WorkQueue {
+    fn default() -> Self {
+        WorkQueue {
+            inner: Default::default(),
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            metrics: Arc::new(NoopMetrics),
+        }
+    }
+}
+
+impl WorkQueue {
+    /// Create an empty work queue with the default backoff parameters and no
+    /// metrics collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report queue transitions (items added/dequeued/requeued, queue depth)
+    /// to `metrics` instead of the default no-op implementation.
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Queue `key` for reconciliation, unless it is already waiting or currently
+    /// being processed. A key that arrives while its previous occurrence is still
+    /// `processing` is marked dirty so `done` re-queues it once, coalescing bursts
+    /// into a single reconcile.
+    pub async fn add(&self, key: ObjectKey) {
+        let mut inner = self.inner.lock().await;
+        if inner.dirty.contains(&key) {
+            return;
+        }
+        inner.dirty.insert(key.clone());
+        if inner.processing.contains(&key) {
+            return;
+        }
+        inner.ready.push_back(key);
+        self.metrics.item_added();
+        self.metrics.queue_depth(inner.ready.len());
+    }
+
+    /// Pop the next ready key, moving it from `dirty` into `processing`.
+    pub async fn get(&self) -> Option<ObjectKey> {
+        let mut inner = self.inner.lock().await;
+        let key = inner.ready.pop_front()?;
+        inner.dirty.remove(&key);
+        inner.processing.insert(key.clone());
+        self.metrics.item_dequeued();
+        self.metrics.queue_depth(inner.ready.len());
+        Some(key)
+    }
+
+    /// Mark `key` as finished processing. If it was re-added while it was being
+    /// processed, re-queue it now instead of dropping the update on the floor.
+    pub async fn done(&self, key: &ObjectKey) {
+        let mut inner = self.inner.lock().await;
+        inner.processing.remove(key);
+        if inner.dirty.contains(key) {
+            inner.ready.push_back(key.clone());
+            self.metrics.item_added();
+            self.metrics.queue_depth(inner.ready.len());
+        }
+    }
+
+    /// Forget a key's failure count, e.g. after it reconciles successfully.
+    pub async fn forget(&self, key: &ObjectKey) {
+        self.inner.lock().await.failures.remove(key);
+    }
+
+    /// Schedule `key` to be added to the queue after `delay` elapses.
+    pub async fn add_after(&self, key: ObjectKey, delay: Duration) {
+        let ready_at = Instant::now() + delay;
+        self.inner
+            .lock()
+            .await
+            .delayed
+            .push(Reverse(DelayedEntry { ready_at, key }));
+        self.metrics.item_requeued();
+    }
+
+    /// Schedule `key` with an exponentially increasing delay based on its failure
+    /// count (`base * 2^failures`, capped at `max_delay`), and bump that count.
+    pub async fn add_rate_limited(&self, key: ObjectKey) {
+        let delay = {
+            let mut inner = self.inner.lock().await;
+            let failures = inner.failures.entry(key.clone()).or_insert(0);
+            let exp = (*failures).min(30);
+            *failures += 1;
+            self.base_delay
+                .checked_mul(1u32 << exp)
+                .unwrap_or(self.max_delay)
+                .min(self.max_delay)
+        };
+        self.add_after(key, delay).await;
+    }
+
+    /// Spawn the background task draining ready delayed entries into the queue.
+    /// Call this once per `WorkQueue` after constructing it.
+    pub fn spawn_delay_driver(&self) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let ready_key = {
+                    let mut inner = this.inner.lock().await;
+                    match inner.delayed.peek() {
+                        Some(Reverse(entry)) if entry.ready_at <= Instant::now() => {
+                            inner.delayed.pop().map(|Reverse(entry)| entry.key)
+                        }
+                        _ => None,
+                    }
+                };
+                match ready_key {
+                    Some(key) => this.add(key).await,
+                    None => tokio::time::delay_for(DELAY_DRIVER_TICK).await,
+                }
+            }
+        });
+    }
+}
+
+fn key(name: &str) -> ObjectKey {
+    ObjectKey {
+        name: name.into(),
+        namespace: None,
+    }
+}
+
+#[tokio::test]
+async fn add_dedupes_while_still_ready() {
+    let queue = WorkQueue::new();
+    queue.add(key("a")).await;
+    queue.add(key("a")).await;
+    assert_eq!(queue.get().await, Some(key("a")));
+    assert_eq!(queue.get().await, None);
+}
+
+#[tokio::test]
+async fn add_while_processing_is_deferred_until_done() {
+    let queue = WorkQueue::new();
+    queue.add(key("a")).await;
+    assert_eq!(queue.get().await, Some(key("a")));
+
+    // re-added while still `processing`: must not show up in `ready` yet...
+    queue.add(key("a")).await;
+    assert_eq!(queue.inner.lock().await.ready.len(), 0);
+
+    // ...but `done` re-queues it since it was marked dirty in the meantime.
+    queue.done(&key("a")).await;
+    assert_eq!(queue.get().await, Some(key("a")));
+}
+
+#[tokio::test]
+async fn done_without_a_re_add_does_not_requeue() {
+    let queue = WorkQueue::new();
+    queue.add(key("a")).await;
+    queue.get().await;
+    queue.done(&key("a")).await;
+    assert_eq!(queue.get().await, None);
+}
+
+#[tokio::test]
+async fn add_rate_limited_backs_off_exponentially_and_forget_resets_it() {
+    let queue = WorkQueue::new();
+    queue.add_rate_limited(key("a")).await;
+    assert_eq!(*queue.inner.lock().await.failures.get(&key("a")).unwrap(), 1);
+    queue.add_rate_limited(key("a")).await;
+    assert_eq!(*queue.inner.lock().await.failures.get(&key("a")).unwrap(), 2);
+
+    queue.forget(&key("a")).await;
+    assert!(queue.inner.lock().await.failures.get(&key("a")).is_none());
+}
+
+#[tokio::test]
+async fn done_on_a_dirty_re_add_reports_it_as_an_item_added() {
+    let metrics = Arc::new(crate::runtime::metrics::Counters::default());
+    let queue = WorkQueue::new().with_metrics(metrics.clone());
+
+    queue.add(key("a")).await;
+    assert_eq!(metrics.items_added.load(std::sync::atomic::Ordering::Relaxed), 1);
+    queue.get().await;
+
+    // re-added while still `processing`: not yet visible as an `add`...
+    queue.add(key("a")).await;
+    assert_eq!(metrics.items_added.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+    // ...but `done` re-queuing it counts as one, per `Metrics::item_added`'s doc.
+    queue.done(&key("a")).await;
+    assert_eq!(metrics.items_added.load(std::sync::atomic::Ordering::Relaxed), 2);
+}